@@ -1,49 +1,197 @@
+use actix_multipart::Multipart;
 use actix_web::{web, HttpResponse};
 use bson::{doc, oid::ObjectId};
+use bytes::BytesMut;
 use chrono::Utc;
 use futures::stream::TryStreamExt;
 use mongodb::options::FindOptions;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
 
 use crate::models::*;
 use crate::AppState;
 
+/// Streams the incoming `multipart/form-data` body straight to a temp file on
+/// disk so the whole upload never has to live in memory at once, enforcing
+/// `AppState::max_upload_bytes` as bytes arrive rather than after the fact.
 pub async fn upload_file(
     state: web::Data<AppState>,
-    query: web::Query<UploadRequest>,
+    mut payload: Multipart,
 ) -> HttpResponse {
     let collection = state.db.collection::<File>("files");
 
-    // In production, handle multipart upload and S3 storage
+    let mut workspace_id: Option<String> = None;
+    let mut channel_id: Option<String> = None;
+    let mut uploaded_by: Option<String> = None;
+    let mut original_name = "upload".to_string();
+    let mut mime_type = "application/octet-stream".to_string();
+    let mut tmp_path: Option<std::path::PathBuf> = None;
+    let mut size: u64 = 0;
+    let mut hasher = Sha256::new();
+
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let disposition = field.content_disposition().clone();
+        let field_name = disposition.get_name().unwrap_or("").to_string();
+
+        if field_name == "file" {
+            original_name = disposition.get_filename().unwrap_or("upload").to_string();
+            mime_type = field
+                .content_type()
+                .map(|m| m.to_string())
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+
+            let path = std::env::temp_dir().join(format!("upload_{}", uuid::Uuid::new_v4()));
+            let mut tmp = match tokio::fs::File::create(&path).await {
+                Ok(f) => f,
+                Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+            };
+
+            while let Some(chunk) = field.try_next().await.transpose() {
+                let chunk = match chunk {
+                    Ok(c) => c,
+                    Err(e) => {
+                        let _ = tokio::fs::remove_file(&path).await;
+                        return HttpResponse::BadRequest().json(serde_json::json!({"error": e.to_string()}));
+                    }
+                };
+
+                size += chunk.len() as u64;
+                if size > state.max_upload_bytes {
+                    let _ = tokio::fs::remove_file(&path).await;
+                    return HttpResponse::PayloadTooLarge()
+                        .json(serde_json::json!({"error": "file exceeds maximum upload size"}));
+                }
+                hasher.update(&chunk);
+                if let Err(e) = tmp.write_all(&chunk).await {
+                    let _ = tokio::fs::remove_file(&path).await;
+                    return HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()}));
+                }
+            }
+
+            tmp_path = Some(path);
+        } else {
+            let mut value = BytesMut::new();
+            while let Some(chunk) = field.try_next().await.transpose() {
+                match chunk {
+                    Ok(c) => value.extend_from_slice(&c),
+                    Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({"error": e.to_string()})),
+                }
+            }
+            let value = String::from_utf8_lossy(&value).into_owned();
+            match field_name.as_str() {
+                "workspace_id" => workspace_id = Some(value),
+                "channel_id" => channel_id = Some(value),
+                "uploaded_by" => uploaded_by = Some(value),
+                _ => {}
+            }
+        }
+    }
+
+    let Some(tmp_path) = tmp_path else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "missing file part"}));
+    };
+    let Some(workspace_id) = workspace_id else {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "missing workspace_id"}));
+    };
+    let Some(uploaded_by) = uploaded_by else {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "missing uploaded_by"}));
+    };
+
+    let checksum = state.checksum_encoding.encode(&hasher.finalize());
+
+    // Content-addressed dedup: if a non-deleted file with the same checksum
+    // already exists in this workspace, reuse its blob instead of writing a
+    // second copy, and record this upload as a lightweight alias.
+    let existing = collection
+        .find_one(
+            doc! { "workspace_id": &workspace_id, "checksum": &checksum, "deleted_at": null },
+            None,
+        )
+        .await
+        .unwrap_or(None);
+
+    let storage_key = if let Some(existing) = &existing {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+        existing.storage_key.clone()
+    } else {
+        let storage_key = format!("files/{}/{}/{}", workspace_id, &checksum[..2.min(checksum.len())], checksum);
+        let put_result = match tokio::fs::File::open(&tmp_path).await {
+            Ok(mut tmp_file) => state.store.put(&storage_key, &mut tmp_file, size).await,
+            Err(e) => Err(crate::store::StoreError::Io(e.to_string())),
+        };
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+        if let Err(e) = put_result {
+            return HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()}));
+        }
+        storage_key
+    };
+
     let file = File {
         id: None,
         name: format!("file_{}", uuid::Uuid::new_v4()),
-        original_name: "uploaded_file".to_string(),
-        mime_type: "application/octet-stream".to_string(),
-        size: 0,
-        storage_key: format!("files/{}/{}", query.workspace_id, uuid::Uuid::new_v4()),
+        original_name,
+        mime_type,
+        size,
+        storage_key,
         url: "".to_string(),
-        thumbnail_url: None,
-        workspace_id: query.workspace_id.clone(),
-        channel_id: query.channel_id.clone(),
-        uploaded_by: query.uploaded_by.clone(),
-        checksum: "".to_string(),
-        metadata: FileMetadata::default(),
+        thumbnail_url: existing.as_ref().and_then(|e| e.thumbnail_url.clone()),
+        workspace_id,
+        channel_id,
+        uploaded_by,
+        checksum,
+        metadata: existing.as_ref().map(|e| e.metadata.clone()).unwrap_or_default(),
+        processing_status: existing.as_ref().map(|e| e.processing_status).unwrap_or_default(),
         is_public: false,
+        shares: existing.as_ref().map(|e| e.shares.clone()).unwrap_or_default(),
         deleted_at: None,
         created_at: Utc::now(),
         updated_at: Utc::now(),
     };
+    let is_new_blob = existing.is_none();
 
     match collection.insert_one(file.clone(), None).await {
         Ok(result) => {
             let mut f = file;
             f.id = result.inserted_id.as_object_id();
+            if is_new_blob {
+                if let (Some(id), Some(redis)) = (f.id, &state.redis) {
+                    enqueue_processing_job(redis, &id.to_hex()).await;
+                }
+            }
             HttpResponse::Created().json(f)
         }
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
     }
 }
 
+/// Pushes a processing job onto the Redis-backed queue; failures are logged
+/// rather than surfaced to the caller, since the upload itself already
+/// succeeded. A job that fails to enqueue is not retried, so that file stays
+/// in `Pending`.
+async fn enqueue_processing_job(redis: &redis::Client, file_id: &str) {
+    let job = ProcessingJob { file_id: file_id.to_string() };
+    let payload = match serde_json::to_string(&job) {
+        Ok(p) => p,
+        Err(e) => {
+            tracing::warn!("failed to serialize processing job: {e}");
+            return;
+        }
+    };
+
+    match redis.get_multiplexed_async_connection().await {
+        Ok(mut conn) => {
+            let result: redis::RedisResult<()> =
+                redis::cmd("RPUSH").arg("file_processing_jobs").arg(payload).query_async(&mut conn).await;
+            if let Err(e) = result {
+                tracing::warn!("failed to enqueue processing job: {e}");
+            }
+        }
+        Err(e) => tracing::warn!("failed to connect to redis: {e}"),
+    }
+}
+
 pub async fn get_file(
     state: web::Data<AppState>,
     path: web::Path<String>,
@@ -108,19 +256,45 @@ pub async fn delete_file(
         Err(_) => return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid ID"})),
     };
 
+    let file = collection.find_one(doc! { "_id": object_id }, None).await.unwrap_or(None);
+
     match collection.update_one(
         doc! { "_id": object_id },
         doc! { "$set": { "deleted_at": Utc::now() } },
         None
     ).await {
-        Ok(_) => HttpResponse::NoContent().finish(),
+        Ok(_) => {
+            // Dedup means several documents can share a storage_key; only
+            // reclaim the blob once no other live document still points at it.
+            if let Some(file) = file {
+                let other_refs = collection
+                    .count_documents(
+                        doc! { "storage_key": &file.storage_key, "deleted_at": null, "_id": { "$ne": object_id } },
+                        None,
+                    )
+                    .await
+                    .unwrap_or(1);
+                if other_refs == 0 {
+                    if let Err(e) = state.store.delete(&file.storage_key).await {
+                        tracing::warn!("failed to delete blob {}: {e}", file.storage_key);
+                    }
+                }
+            }
+            HttpResponse::NoContent().finish()
+        }
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
     }
 }
 
+/// Serves file bytes directly so `Range` requests (seekable audio/video,
+/// resumable downloads) work regardless of the storage backend. Public
+/// objects still take the cheaper redirect path since a CDN/S3 URL can
+/// serve those directly.
 pub async fn download_file(
+    req: actix_web::HttpRequest,
     state: web::Data<AppState>,
     path: web::Path<String>,
+    query: web::Query<DownloadQueryParams>,
 ) -> HttpResponse {
     let collection = state.db.collection::<File>("files");
     let id = path.into_inner();
@@ -130,39 +304,178 @@ pub async fn download_file(
         Err(_) => return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid ID"})),
     };
 
-    match collection.find_one(doc! { "_id": object_id }, None).await {
-        Ok(Some(file)) => {
-            // Generate presigned URL from S3
-            let presigned_url = format!("https://{}.s3.amazonaws.com/{}", state.s3_bucket, file.storage_key);
-            HttpResponse::TemporaryRedirect()
-                .append_header(("Location", presigned_url))
-                .finish()
+    let file = match collection.find_one(doc! { "_id": object_id }, None).await {
+        Ok(Some(file)) => file,
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({"error": "File not found"})),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+    };
+
+    // A token identifies a share link and is only required for recipients
+    // downloading via one; the uploader/workspace hitting the plain
+    // `/download` URL (the same path `get_file` always returns) needs no
+    // token at all, matching how this endpoint behaved before share links
+    // existed.
+    let now = Utc::now();
+    let share = match query.token.as_deref() {
+        Some(token) => match file.shares.iter().find(|s| s.token == token) {
+            Some(share) if share.is_valid(now) => Some(share),
+            Some(_) => {
+                return HttpResponse::Forbidden().json(serde_json::json!({"error": "share link expired or exhausted"}))
+            }
+            None => return HttpResponse::Forbidden().json(serde_json::json!({"error": "invalid share token"})),
+        },
+        None => None,
+    };
+
+    if let Some(share) = share {
+        if let Err(e) = collection
+            .update_one(
+                doc! { "_id": object_id, "shares.token": &share.token },
+                doc! { "$inc": { "shares.$.download_count": 1 } },
+                None,
+            )
+            .await
+        {
+            tracing::warn!("failed to record share download for file {}: {e}", id);
+        }
+    }
+
+    let serving_thumbnail = query.variant.as_deref() == Some("thumbnail");
+    let (storage_key, mime_type) = if serving_thumbnail {
+        (format!("{}.thumb.jpg", file.storage_key), "image/jpeg".to_string())
+    } else {
+        (file.storage_key.clone(), file.mime_type.clone())
+    };
+
+    if file.is_public {
+        if let Ok(url) = state.store.presign_get(&storage_key, std::time::Duration::from_secs(3600)).await {
+            return HttpResponse::TemporaryRedirect().append_header(("Location", url)).finish();
+        }
+    }
+
+    let bytes = match state.store.get(&storage_key).await {
+        Ok(bytes) => bytes,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+    };
+
+    let last_modified = file.updated_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let range = req.headers().get("range").and_then(|v| v.to_str().ok()).and_then(parse_range);
+
+    let range = range.map(|(start, end)| (start, end.min(bytes.len().saturating_sub(1) as u64)));
+
+    match range {
+        Some((start, end)) if (start as usize) < bytes.len() && start <= end => {
+            let (start, end) = (start as usize, end as usize);
+            HttpResponse::PartialContent()
+                .append_header(("Content-Range", format!("bytes {}-{}/{}", start, end, bytes.len())))
+                .append_header(("Accept-Ranges", "bytes"))
+                .append_header(("Last-Modified", last_modified))
+                .append_header(("Cache-Control", "private, max-age=3600"))
+                .content_type(mime_type)
+                .body(bytes[start..=end].to_vec())
+        }
+        Some(_) => HttpResponse::build(actix_web::http::StatusCode::RANGE_NOT_SATISFIABLE)
+            .append_header(("Content-Range", format!("bytes */{}", bytes.len())))
+            .finish(),
+        None => {
+            if !serving_thumbnail && !file.checksum.is_empty() {
+                use sha2::{Digest, Sha256};
+                let mut hasher = Sha256::new();
+                hasher.update(&bytes);
+                let computed = state.checksum_encoding.encode(&hasher.finalize());
+                if computed != file.checksum {
+                    tracing::error!("checksum mismatch for file {}: stored object does not match recorded digest", id);
+                }
+            }
+
+            HttpResponse::Ok()
+                .append_header(("Accept-Ranges", "bytes"))
+                .append_header(("Last-Modified", last_modified))
+                .append_header(("Cache-Control", "private, max-age=3600"))
+                .content_type(mime_type)
+                .body(bytes)
         }
-        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({"error": "File not found"})),
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
     }
 }
 
+/// Parses a single-range `Range: bytes=start-end` header value. Multi-range
+/// requests and unbounded-length shorthands beyond `start-` are not
+/// supported; callers fall back to serving the whole body for those.
+fn parse_range(value: &str) -> Option<(u64, u64)> {
+    let spec = value.strip_prefix("bytes=")?;
+    let (start, end) = spec.split_once('-')?;
+    let start: u64 = start.parse().ok()?;
+    if end.is_empty() {
+        return Some((start, u64::MAX));
+    }
+    let end: u64 = end.parse().ok()?;
+    Some((start, end))
+}
+
+/// Creates a tokenized share link instead of flipping the old irreversible
+/// `is_public` flag: the link carries its own expiry and download cap and
+/// can be revoked independently of any other share on the same file.
 pub async fn share_file(
     state: web::Data<AppState>,
     path: web::Path<String>,
+    body: Option<web::Json<CreateShareRequest>>,
 ) -> HttpResponse {
     let collection = state.db.collection::<File>("files");
     let id = path.into_inner();
+    let req = body.map(|b| b.into_inner()).unwrap_or_default();
 
     let object_id = match ObjectId::parse_str(&id) {
         Ok(oid) => oid,
         Err(_) => return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid ID"})),
     };
 
+    let share = ShareLink {
+        token: uuid::Uuid::new_v4().simple().to_string(),
+        delete_token: uuid::Uuid::new_v4().simple().to_string(),
+        created_at: Utc::now(),
+        expires_at: req.expires_in_secs.map(|secs| Utc::now() + chrono::Duration::seconds(secs)),
+        max_downloads: req.max_downloads,
+        download_count: 0,
+    };
+    let share_doc = match bson::to_bson(&share) {
+        Ok(doc) => doc,
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+    };
+
     match collection.update_one(
         doc! { "_id": object_id },
-        doc! { "$set": { "is_public": true } },
+        doc! { "$push": { "shares": share_doc } },
         None
     ).await {
         Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "share_url": format!("/api/v1/files/{}/download", id)
+            "share_url": format!("/api/v1/files/{}/download?token={}", id, share.token),
+            "revoke_url": format!("/api/v1/files/{}/share/{}", id, share.delete_token),
+            "expires_at": share.expires_at,
         })),
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
     }
 }
+
+/// Revokes a single share link by its delete token, leaving any other
+/// shares on the same file untouched.
+pub async fn revoke_share(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (id, delete_token) = path.into_inner();
+    let collection = state.db.collection::<File>("files");
+
+    let object_id = match ObjectId::parse_str(&id) {
+        Ok(oid) => oid,
+        Err(_) => return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid ID"})),
+    };
+
+    match collection.update_one(
+        doc! { "_id": object_id },
+        doc! { "$pull": { "shares": { "delete_token": &delete_token } } },
+        None,
+    ).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+    }
+}
@@ -0,0 +1,123 @@
+//! Minimal BlurHash encoder: downsamples an image into a small grid of DCT
+//! basis-function coefficients and packs them into a short base83 string,
+//! per the algorithm described at https://blurha.sh.
+
+use image::{DynamicImage, GenericImageView};
+
+const BASE83_CHARS: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// Encodes `img` into a BlurHash using `components_x` x `components_y` DCT
+/// components (typically 4x3). Returns `None` for degenerate (zero-sized)
+/// images.
+pub fn encode(img: &DynamicImage, components_x: u32, components_y: u32) -> Option<String> {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let small = img.thumbnail(32, 32);
+    let (width, height) = small.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let rgb = small.to_rgb8();
+
+    let mut factors = Vec::with_capacity((components_x * components_y) as usize);
+    for j in 0..components_y {
+        for i in 0..components_x {
+            factors.push(basis_function(&rgb, width, height, i, j));
+        }
+    }
+
+    let dc = factors[0];
+    let ac = &factors[1..];
+
+    let mut result = String::new();
+    let size_flag = (components_x - 1) + (components_y - 1) * 9;
+    result.push_str(&encode83(size_flag as u64, 1));
+
+    let max_ac = ac.iter().fold(0.0_f32, |acc, (r, g, b)| acc.max(r.abs()).max(g.abs()).max(b.abs()));
+    let quantized_max_ac = if ac.is_empty() {
+        0
+    } else {
+        ((max_ac * 166.0 - 0.5).clamp(0.0, 82.0)) as u64
+    };
+    result.push_str(&encode83(quantized_max_ac, 1));
+
+    result.push_str(&encode83(encode_dc(dc), 4));
+
+    let ac_max_value = (quantized_max_ac as f32 + 1.0) / 166.0;
+    for &component in ac {
+        result.push_str(&encode83(encode_ac(component, ac_max_value), 2));
+    }
+
+    Some(result)
+}
+
+fn basis_function(
+    img: &image::RgbImage,
+    width: u32,
+    height: u32,
+    i: u32,
+    j: u32,
+) -> (f32, f32, f32) {
+    let mut r = 0.0_f32;
+    let mut g = 0.0_f32;
+    let mut b = 0.0_f32;
+    let normalization = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+
+    for y in 0..height {
+        for x in 0..width {
+            let basis = normalization
+                * (std::f32::consts::PI * i as f32 * x as f32 / width as f32).cos()
+                * (std::f32::consts::PI * j as f32 * y as f32 / height as f32).cos();
+            let pixel = img.get_pixel(x, y);
+            r += basis * srgb_to_linear(pixel[0]);
+            g += basis * srgb_to_linear(pixel[1]);
+            b += basis * srgb_to_linear(pixel[2]);
+        }
+    }
+
+    let scale = 1.0 / (width as f32 * height as f32);
+    (r * scale, g * scale, b * scale)
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let v = value as f32 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let srgb = if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 };
+    (srgb * 255.0).round() as u8
+}
+
+fn encode_dc((r, g, b): (f32, f32, f32)) -> u64 {
+    let r = linear_to_srgb(r) as u64;
+    let g = linear_to_srgb(g) as u64;
+    let b = linear_to_srgb(b) as u64;
+    (r << 16) + (g << 8) + b
+}
+
+fn encode_ac((r, g, b): (f32, f32, f32), max_value: f32) -> u64 {
+    let quantize = |v: f32| -> u64 {
+        ((v / max_value).signum() * (v / max_value).abs().powf(0.5) * 9.0 + 9.5).clamp(0.0, 18.0) as u64
+    };
+    quantize(r) * 19 * 19 + quantize(g) * 19 + quantize(b)
+}
+
+fn encode83(value: u64, length: usize) -> String {
+    let mut result = vec![0u8; length];
+    let mut value = value;
+    for i in (0..length).rev() {
+        let digit = (value % 83) as usize;
+        result[i] = BASE83_CHARS[digit];
+        value /= 83;
+    }
+    String::from_utf8(result).unwrap()
+}
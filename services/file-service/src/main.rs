@@ -3,11 +3,43 @@ use mongodb::{Client, options::ClientOptions, Database};
 use std::env;
 
 mod api;
+mod blurhash;
 mod models;
+mod store;
+mod worker;
+
+use store::{LocalStore, S3Store, Store, UrlStyle};
 
 pub struct AppState {
     pub db: Database,
-    pub s3_bucket: String,
+    pub store: Box<dyn Store>,
+    pub max_upload_bytes: u64,
+    pub checksum_encoding: ChecksumEncoding,
+    pub redis: Option<redis::Client>,
+}
+
+/// Text encoding used when rendering a content digest into `File::checksum`
+/// and its content-addressed `storage_key`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumEncoding {
+    Hex,
+    Base58,
+}
+
+impl ChecksumEncoding {
+    fn from_env() -> Self {
+        match env::var("CHECKSUM_ENCODING").as_deref() {
+            Ok("base58") => ChecksumEncoding::Base58,
+            _ => ChecksumEncoding::Hex,
+        }
+    }
+
+    pub fn encode(self, digest: &[u8]) -> String {
+        match self {
+            ChecksumEncoding::Hex => hex::encode(digest),
+            ChecksumEncoding::Base58 => bs58::encode(digest).into_string(),
+        }
+    }
 }
 
 #[actix_web::main]
@@ -18,13 +50,29 @@ async fn main() -> std::io::Result<()> {
     let mongo_uri = env::var("MONGODB_URI").unwrap_or_else(|_| "mongodb://localhost:27017".to_string());
     let db_name = env::var("DATABASE_NAME").unwrap_or_else(|_| "quckchat_files".to_string());
     let port = env::var("PORT").unwrap_or_else(|_| "3011".to_string());
-    let s3_bucket = env::var("S3_BUCKET").unwrap_or_else(|_| "quckchat-files".to_string());
+    let max_upload_bytes: u64 = env::var("MAX_UPLOAD_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(100 * 1024 * 1024);
+    let checksum_encoding = ChecksumEncoding::from_env();
+    let redis = env::var("REDIS_URL").ok().and_then(|url| redis::Client::open(url).ok());
+    let store: Box<dyn Store> = build_store();
 
     let client_options = ClientOptions::parse(&mongo_uri).await.expect("Failed to parse MongoDB URI");
     let client = Client::with_options(client_options).expect("Failed to create MongoDB client");
     let db = client.database(&db_name);
 
-    let state = web::Data::new(AppState { db, s3_bucket });
+    let state = web::Data::new(AppState {
+        db,
+        store,
+        max_upload_bytes,
+        checksum_encoding,
+        redis,
+    });
+
+    if state.redis.is_some() {
+        actix_web::rt::spawn(worker::run(state.clone()));
+    }
 
     tracing::info!("File service starting on port {}", port);
 
@@ -41,6 +89,7 @@ async fn main() -> std::io::Result<()> {
                     .route("/{id}", web::delete().to(api::delete_file))
                     .route("/{id}/download", web::get().to(api::download_file))
                     .route("/{id}/share", web::post().to(api::share_file))
+                    .route("/{id}/share/{token}", web::delete().to(api::revoke_share))
             )
     })
     .bind(format!("0.0.0.0:{}", port))?
@@ -51,3 +100,30 @@ async fn main() -> std::io::Result<()> {
 async fn health_check() -> HttpResponse {
     HttpResponse::Ok().json(serde_json::json!({"status": "healthy", "service": "file-service"}))
 }
+
+/// Picks the storage backend from `STORAGE_BACKEND` (`local` or `s3`,
+/// defaulting to `local`), so swapping backends is a deploy-time config
+/// change rather than a code change.
+fn build_store() -> Box<dyn Store> {
+    match env::var("STORAGE_BACKEND").as_deref() {
+        Ok("s3") => {
+            let url_style = match env::var("S3_URL_STYLE").as_deref() {
+                Ok("path") => UrlStyle::PathStyle,
+                _ => UrlStyle::VirtualHost,
+            };
+            Box::new(S3Store::new(
+                env::var("S3_BUCKET").unwrap_or_else(|_| "quckchat-files".to_string()),
+                env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                env::var("S3_ENDPOINT").unwrap_or_else(|_| "s3.amazonaws.com".to_string()),
+                env::var("S3_ACCESS_KEY").unwrap_or_default(),
+                env::var("S3_SECRET_KEY").unwrap_or_default(),
+                url_style,
+            ))
+        }
+        _ => Box::new(LocalStore {
+            root: env::var("STORAGE_ROOT").unwrap_or_else(|_| "./data/files".to_string()).into(),
+            public_base_url: env::var("PUBLIC_BASE_URL")
+                .unwrap_or_else(|_| "http://localhost:3011/static".to_string()),
+        }),
+    }
+}
@@ -20,13 +20,30 @@ pub struct File {
     pub checksum: String,
     #[serde(default)]
     pub metadata: FileMetadata,
+    #[serde(default)]
+    pub processing_status: ProcessingStatus,
     pub is_public: bool,
+    #[serde(default)]
+    pub shares: Vec<ShareLink>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub deleted_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// Lifecycle of the async media-processing job kicked off after upload.
+/// `get_file` surfaces this so clients can poll until thumbnails/metadata
+/// are ready instead of the response looking silently incomplete.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessingStatus {
+    #[default]
+    Pending,
+    Processing,
+    Complete,
+    Failed,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct FileMetadata {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -37,13 +54,48 @@ pub struct FileMetadata {
     pub duration: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pages: Option<u32>,
+    /// Compact ASCII placeholder (4x3 DCT components) clients can decode into
+    /// a blurry preview while the full image or its thumbnail loads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blurhash: Option<String>,
+}
+
+/// A single tokenized, time-bounded share link. Replaces the old one-way
+/// `is_public` flag: each link is independently revocable and can cap the
+/// number of downloads, so sharing a file no longer means leaking it forever.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShareLink {
+    pub token: String,
+    pub delete_token: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_downloads: Option<u32>,
+    #[serde(default)]
+    pub download_count: u32,
+}
+
+impl ShareLink {
+    pub fn is_valid(&self, now: DateTime<Utc>) -> bool {
+        let not_expired = self.expires_at.map(|exp| now < exp).unwrap_or(true);
+        let under_limit = self.max_downloads.map(|max| self.download_count < max).unwrap_or(true);
+        not_expired && under_limit
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct CreateShareRequest {
+    pub expires_in_secs: Option<i64>,
+    pub max_downloads: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct UploadRequest {
-    pub workspace_id: String,
-    pub channel_id: Option<String>,
-    pub uploaded_by: String,
+pub struct DownloadQueryParams {
+    pub token: Option<String>,
+    /// `thumbnail` to fetch the downscaled preview the worker generated
+    /// instead of the original object.
+    pub variant: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -66,3 +118,10 @@ pub struct FilesResponse {
     pub files: Vec<File>,
     pub total: u64,
 }
+
+/// Payload pushed onto the `file_processing_jobs` Redis list so thumbnailing
+/// and metadata extraction happen off the upload request path.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProcessingJob {
+    pub file_id: String,
+}
@@ -0,0 +1,188 @@
+use actix_web::web;
+use bson::{doc, oid::ObjectId};
+use futures::stream::TryStreamExt;
+use std::str::FromStr;
+
+use crate::models::{File, FileMetadata, ProcessingJob, ProcessingStatus};
+use crate::AppState;
+
+/// Background loop that drains `file_processing_jobs` from Redis and runs
+/// the media-processing pipeline for each uploaded file, keeping slow
+/// thumbnailing/probing work off the upload request path.
+pub async fn run(state: web::Data<AppState>) {
+    let Some(redis) = state.redis.clone() else { return };
+
+    loop {
+        let mut conn = match redis.get_multiplexed_async_connection().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::warn!("processing worker: redis connection failed: {e}");
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+
+        let popped: redis::RedisResult<Option<(String, String)>> =
+            redis::cmd("BLPOP").arg("file_processing_jobs").arg(5).query_async(&mut conn).await;
+
+        let payload = match popped {
+            Ok(Some((_key, payload))) => payload,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!("processing worker: BLPOP failed: {e}");
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+
+        let job: ProcessingJob = match serde_json::from_str(&payload) {
+            Ok(job) => job,
+            Err(e) => {
+                tracing::warn!("processing worker: malformed job payload: {e}");
+                continue;
+            }
+        };
+
+        if let Err(e) = process_job(&state, &job).await {
+            tracing::warn!("processing worker: job for file {} failed: {e}", job.file_id);
+        }
+    }
+}
+
+/// Runs the pipeline for the job's file and fans the result out to every
+/// other `File` document that dedup-aliased the same `storage_key`. Dedup
+/// skips enqueuing a job for an alias inserted while the original blob is
+/// still being processed, so without this fan-out that alias would be stuck
+/// showing whatever stale `metadata`/`thumbnail_url` it copied at insert
+/// time even after the original finished.
+async fn process_job(state: &web::Data<AppState>, job: &ProcessingJob) -> Result<(), String> {
+    let collection = state.db.collection::<File>("files");
+    let object_id = ObjectId::from_str(&job.file_id).map_err(|e| e.to_string())?;
+
+    let file = collection
+        .find_one(doc! { "_id": object_id }, None)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("file not found")?;
+
+    collection
+        .update_many(
+            doc! { "storage_key": &file.storage_key },
+            doc! { "$set": { "processing_status": "processing" } },
+            None,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let bytes = state.store.get(&file.storage_key).await.map_err(|e| e.to_string())?;
+    let tmp_path = std::env::temp_dir().join(format!("process_{}", uuid::Uuid::new_v4()));
+    tokio::fs::write(&tmp_path, &bytes).await.map_err(|e| e.to_string())?;
+
+    let metadata = extract_metadata(&file.mime_type, &tmp_path).await;
+    let has_thumbnail = generate_thumbnail(state, &file, &tmp_path).await;
+
+    let _ = tokio::fs::remove_file(&tmp_path).await;
+
+    let mut set = doc! { "processing_status": "complete" };
+    if let Ok(metadata_doc) = bson::to_bson(&metadata) {
+        set.insert("metadata", metadata_doc);
+    }
+
+    collection
+        .update_many(doc! { "storage_key": &file.storage_key }, doc! { "$set": set }, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if has_thumbnail {
+        // Each sibling gets a thumbnail URL built from its own id, so its
+        // own share token (not the original upload's) governs access to it.
+        let siblings: Vec<File> = collection
+            .find(doc! { "storage_key": &file.storage_key }, None)
+            .await
+            .map_err(|e| e.to_string())?
+            .try_collect()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        for sibling in siblings {
+            let Some(sibling_id) = sibling.id else { continue };
+            let thumbnail_url = format!("/api/v1/files/{}/download?variant=thumbnail", sibling_id.to_hex());
+            let _ = collection
+                .update_one(doc! { "_id": sibling_id }, doc! { "$set": { "thumbnail_url": thumbnail_url } }, None)
+                .await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Dispatches on the file's MIME class: decodes dimensions for images,
+/// probes duration for audio/video via an external ffprobe-style tool, and
+/// counts pages for PDFs.
+async fn extract_metadata(mime_type: &str, path: &std::path::Path) -> FileMetadata {
+    if mime_type.starts_with("image/") {
+        if let Ok(dimensions) = image::image_dimensions(path) {
+            let blurhash = image::open(path).ok().and_then(|img| crate::blurhash::encode(&img, 4, 3));
+            return FileMetadata {
+                width: Some(dimensions.0),
+                height: Some(dimensions.1),
+                blurhash,
+                ..Default::default()
+            };
+        }
+    } else if mime_type.starts_with("video/") || mime_type.starts_with("audio/") {
+        if let Some(duration) = probe_duration(path).await {
+            return FileMetadata { duration: Some(duration), ..Default::default() };
+        }
+    } else if mime_type == "application/pdf" {
+        if let Some(pages) = count_pdf_pages(path) {
+            return FileMetadata { pages: Some(pages), ..Default::default() };
+        }
+    }
+    FileMetadata::default()
+}
+
+async fn probe_duration(path: &std::path::Path) -> Option<f64> {
+    let output = tokio::process::Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration", "-of", "csv=p=0"])
+        .arg(path)
+        .output()
+        .await
+        .ok()?;
+    String::from_utf8(output.stdout).ok()?.trim().parse().ok()
+}
+
+fn count_pdf_pages(path: &std::path::Path) -> Option<u32> {
+    let bytes = std::fs::read(path).ok()?;
+    let needle = b"/Type/Page";
+    // Skip `/Type/Pages` (page-tree nodes), which share the same prefix.
+    let count = bytes
+        .windows(needle.len() + 1)
+        .filter(|w| &w[..needle.len()] == needle && w[needle.len()] != b's')
+        .count();
+    Some(count as u32)
+}
+
+/// Writes the downscaled preview to `{storage_key}.thumb.jpg` and reports
+/// whether one now exists for this blob. The per-document `thumbnail_url`
+/// pointing at it is assigned separately, by the caller, for every document
+/// sharing this `storage_key`.
+async fn generate_thumbnail(state: &web::Data<AppState>, file: &File, source_path: &std::path::Path) -> bool {
+    if !file.mime_type.starts_with("image/") {
+        return false;
+    }
+    let Ok(img) = image::open(source_path) else { return false };
+    let thumbnail = img.thumbnail(256, 256);
+    let mut jpeg_bytes: Vec<u8> = Vec::new();
+    if thumbnail
+        .write_to(&mut std::io::Cursor::new(&mut jpeg_bytes), image::ImageOutputFormat::Jpeg(85))
+        .is_err()
+    {
+        return false;
+    }
+
+    let thumb_key = format!("{}.thumb.jpg", file.storage_key);
+    let len = jpeg_bytes.len() as u64;
+    let mut reader = std::io::Cursor::new(jpeg_bytes);
+    state.store.put(&thumb_key, &mut reader, len).await.is_ok()
+}
@@ -1,5 +1,7 @@
+use actix_multipart::Multipart;
 use actix_web::{web, HttpResponse};
 use bson::{doc, oid::ObjectId};
+use bytes::BytesMut;
 use chrono::Utc;
 use futures::stream::TryStreamExt;
 use mongodb::options::FindOptions;
@@ -26,7 +28,9 @@ pub async fn create_message(
         reactions: vec![],
         edited_at: None,
         deleted_at: None,
-        is_pinned: false,
+        flags: MessageFlags::empty(),
+        poll: None,
+        remote_id: None,
         created_at: Utc::now(),
         updated_at: Utc::now(),
     };
@@ -35,6 +39,8 @@ pub async fn create_message(
         Ok(result) => {
             let mut msg = message;
             msg.id = result.inserted_id.as_object_id();
+            crate::gateway::publish(&state, Event::MessageCreate { payload: msg.clone() }).await;
+            crate::federation::fan_out_create(&state, &msg).await;
             HttpResponse::Created().json(msg)
         }
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
@@ -43,6 +49,244 @@ pub async fn create_message(
     }
 }
 
+/// Accepts a raw file part plus a `payload_json` part (the same shape as
+/// `CreateMessageRequest`), mirroring Discord-style attachment uploads.
+/// Computes attachment metadata — dimensions for images/video, duration and
+/// a downsampled waveform for audio, a thumbnail for visual media — instead
+/// of leaving the message with a generic, data-less file chip.
+pub async fn create_message_with_attachment(
+    state: web::Data<AppState>,
+    mut payload: Multipart,
+) -> HttpResponse {
+    let mut request: Option<CreateMessageRequest> = None;
+    let mut file_bytes: Vec<u8> = Vec::new();
+    let mut file_name = "attachment".to_string();
+    let mut mime_type = "application/octet-stream".to_string();
+    let mut has_file = false;
+
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let disposition = field.content_disposition().clone();
+        let field_name = disposition.get_name().unwrap_or("").to_string();
+
+        let mut value = BytesMut::new();
+        while let Some(chunk) = field.try_next().await.transpose() {
+            match chunk {
+                Ok(c) => value.extend_from_slice(&c),
+                Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({"error": e.to_string()})),
+            }
+        }
+
+        match field_name.as_str() {
+            "payload_json" => {
+                request = match serde_json::from_slice(&value) {
+                    Ok(r) => Some(r),
+                    Err(e) => {
+                        return HttpResponse::BadRequest()
+                            .json(serde_json::json!({"error": format!("invalid payload_json: {e}")}))
+                    }
+                };
+            }
+            "file" => {
+                file_name = disposition.get_filename().unwrap_or("attachment").to_string();
+                mime_type = field
+                    .content_type()
+                    .map(|m| m.to_string())
+                    .unwrap_or_else(|| "application/octet-stream".to_string());
+                file_bytes = value.to_vec();
+                has_file = true;
+            }
+            _ => {}
+        }
+    }
+
+    let Some(request) = request else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "missing payload_json part"}));
+    };
+    if !has_file {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "missing file part"}));
+    }
+
+    let attachment = build_attachment(&file_name, &mime_type, &file_bytes).await;
+
+    let collection = state.db.collection::<Message>("messages");
+    let message_type = match mime_type.split('/').next() {
+        Some("image") => MessageType::Image,
+        Some("video") => MessageType::Video,
+        Some("audio") => MessageType::Audio,
+        _ => MessageType::File,
+    };
+
+    let message = Message {
+        id: None,
+        channel_id: request.channel_id,
+        user_id: request.user_id,
+        content: request.content,
+        thread_id: request.thread_id,
+        parent_message_id: request.parent_message_id,
+        message_type,
+        attachments: vec![attachment],
+        mentions: request.mentions,
+        reactions: vec![],
+        edited_at: None,
+        deleted_at: None,
+        flags: MessageFlags::empty(),
+        poll: None,
+        remote_id: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+
+    match collection.insert_one(message.clone(), None).await {
+        Ok(result) => {
+            let mut msg = message;
+            msg.id = result.inserted_id.as_object_id();
+            crate::gateway::publish(&state, Event::MessageCreate { payload: msg.clone() }).await;
+            crate::federation::fan_out_create(&state, &msg).await;
+            HttpResponse::Created().json(msg)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": format!("Failed to create message: {}", e)
+        })),
+    }
+}
+
+/// Persists the raw bytes to local disk (attachments are small relative to
+/// the bulk uploads the file-service handles) and derives whatever metadata
+/// applies to the MIME class.
+async fn build_attachment(file_name: &str, mime_type: &str, bytes: &[u8]) -> Attachment {
+    let attachments_root = std::env::var("MESSAGE_ATTACHMENTS_ROOT").unwrap_or_else(|_| "./data/attachments".to_string());
+    let id = uuid::Uuid::new_v4().to_string();
+    let storage_path = std::path::Path::new(&attachments_root).join(&id);
+    if let Some(parent) = storage_path.parent() {
+        let _ = tokio::fs::create_dir_all(parent).await;
+    }
+    let _ = tokio::fs::write(&storage_path, bytes).await;
+
+    let mut attachment = Attachment {
+        id: id.clone(),
+        file_type: mime_type.to_string(),
+        file_name: file_name.to_string(),
+        file_size: bytes.len() as u64,
+        url: format!("/api/v1/attachments/{id}"),
+        thumbnail_url: None,
+        width: None,
+        height: None,
+        duration_secs: None,
+        waveform: None,
+    };
+
+    if mime_type.starts_with("image/") {
+        if let Ok(img) = image::load_from_memory(bytes) {
+            attachment.width = Some(img.width());
+            attachment.height = Some(img.height());
+            attachment.thumbnail_url = write_image_thumbnail(&attachments_root, &id, &img).await;
+        }
+    } else if mime_type.starts_with("video/") || mime_type.starts_with("audio/") {
+        attachment.duration_secs = probe_duration(&storage_path).await;
+        if mime_type.starts_with("video/") {
+            if let Some((width, height)) = probe_video_dimensions(&storage_path).await {
+                attachment.width = Some(width);
+                attachment.height = Some(height);
+            }
+            attachment.thumbnail_url = extract_video_thumbnail(&attachments_root, &id, &storage_path).await;
+        } else {
+            attachment.waveform = compute_waveform(bytes, 64);
+        }
+    }
+
+    attachment
+}
+
+async fn probe_duration(path: &std::path::Path) -> Option<f32> {
+    let output = tokio::process::Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration", "-of", "csv=p=0"])
+        .arg(path)
+        .output()
+        .await
+        .ok()?;
+    String::from_utf8(output.stdout).ok()?.trim().parse().ok()
+}
+
+async fn probe_video_dimensions(path: &std::path::Path) -> Option<(u32, u32)> {
+    let output = tokio::process::Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0", "-show_entries", "stream=width,height", "-of", "csv=p=0"])
+        .arg(path)
+        .output()
+        .await
+        .ok()?;
+    let line = String::from_utf8(output.stdout).ok()?;
+    let (width, height) = line.trim().split_once(',')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+/// Downscales a decoded image to a 256x256 JPEG preview and writes it next
+/// to the original attachment, mirroring the file-service's own
+/// `{key}.thumb.jpg` convention.
+async fn write_image_thumbnail(attachments_root: &str, id: &str, img: &image::DynamicImage) -> Option<String> {
+    let thumbnail = img.thumbnail(256, 256);
+    let mut jpeg_bytes: Vec<u8> = Vec::new();
+    thumbnail.write_to(&mut std::io::Cursor::new(&mut jpeg_bytes), image::ImageOutputFormat::Jpeg(85)).ok()?;
+
+    let thumb_path = std::path::Path::new(attachments_root).join(format!("{id}.thumb.jpg"));
+    tokio::fs::write(&thumb_path, &jpeg_bytes).await.ok()?;
+    Some(format!("/api/v1/attachments/{id}/thumbnail"))
+}
+
+/// Grabs a single frame a second into the clip via `ffmpeg` and downscales
+/// it the same way `write_image_thumbnail` does, so video attachments get a
+/// real preview instead of a generic file chip.
+async fn extract_video_thumbnail(attachments_root: &str, id: &str, source_path: &std::path::Path) -> Option<String> {
+    let frame_path = std::env::temp_dir().join(format!("{id}_frame.jpg"));
+    let status = tokio::process::Command::new("ffmpeg")
+        .args(["-y", "-ss", "00:00:01", "-i"])
+        .arg(source_path)
+        .args(["-frames:v", "1", "-f", "image2"])
+        .arg(&frame_path)
+        .output()
+        .await
+        .ok()?;
+    if !status.status.success() {
+        let _ = tokio::fs::remove_file(&frame_path).await;
+        return None;
+    }
+
+    let frame_bytes = tokio::fs::read(&frame_path).await.ok()?;
+    let _ = tokio::fs::remove_file(&frame_path).await;
+    let frame = image::load_from_memory(&frame_bytes).ok()?;
+    write_image_thumbnail(attachments_root, id, &frame).await
+}
+
+/// Downsamples the raw byte stream into `buckets` amplitude values as a
+/// cheap proxy waveform (a true PCM decode would require a codec per
+/// format); good enough to drive a scrubber bar's visual shape.
+fn compute_waveform(bytes: &[u8], buckets: usize) -> Option<String> {
+    if bytes.is_empty() || buckets == 0 {
+        return None;
+    }
+    let chunk_size = (bytes.len() / buckets).max(1);
+    let samples: Vec<u8> = bytes
+        .chunks(chunk_size)
+        .take(buckets)
+        .map(|chunk| chunk.iter().fold(0u8, |acc, &b| acc.max(b.abs_diff(128).saturating_mul(2))))
+        .collect();
+    Some(base64_encode(&samples))
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let b0 = chunk[0];
+        let b1 = *chunk.get(1).unwrap_or(&0);
+        let b2 = *chunk.get(2).unwrap_or(&0);
+        out.push(CHARS[(b0 >> 2) as usize] as char);
+        out.push(CHARS[(((b0 & 0x03) << 4) | (b1 >> 4)) as usize] as char);
+        out.push(if chunk.len() > 1 { CHARS[(((b1 & 0x0f) << 2) | (b2 >> 6)) as usize] as char } else { '=' });
+        out.push(if chunk.len() > 2 { CHARS[(b2 & 0x3f) as usize] as char } else { '=' });
+    }
+    out
+}
+
 pub async fn get_message(
     state: web::Data<AppState>,
     path: web::Path<String>,
@@ -90,12 +334,161 @@ pub async fn list_messages(
                 has_more: messages.len() as i64 == limit,
                 cursor: messages.last().and_then(|m| m.id.map(|id| id.to_hex())),
                 messages,
+                total_hits: None,
+                context: None,
             })
         }
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
     }
 }
 
+/// Structured search over messages: free-text `content` (a MongoDB `$text`
+/// query, which needs a text index on that field; this service does not
+/// create one) combined with author/type/mention/pin/date filters,
+/// returning each hit alongside a small window of surrounding messages so
+/// the UI can render results in their original context.
+pub async fn search_messages(
+    state: web::Data<AppState>,
+    query: web::Query<MessageSearchQuery>,
+) -> HttpResponse {
+    let collection = state.db.collection::<Message>("messages");
+    let limit = query.limit.unwrap_or(25).min(100);
+    let context_window = query.context.unwrap_or(0).clamp(0, 10);
+
+    let mut filter = doc! { "deleted_at": null };
+
+    if let Some(channel_id) = &query.channel_id {
+        filter.insert("channel_id", channel_id);
+    }
+    if let Some(content) = &query.content {
+        filter.insert("$text", doc! { "$search": content });
+    }
+    if let Some(author_ids) = &query.author_ids {
+        let ids: Vec<&str> = author_ids.split(',').filter(|s| !s.is_empty()).collect();
+        if !ids.is_empty() {
+            filter.insert("user_id", doc! { "$in": ids });
+        }
+    }
+    if let Some(message_type) = &query.message_type {
+        if let Ok(bson::Bson::String(s)) = bson::to_bson(message_type) {
+            filter.insert("message_type", s);
+        }
+    }
+    if let Some(mentions) = &query.mentions {
+        let ids: Vec<&str> = mentions.split(',').filter(|s| !s.is_empty()).collect();
+        if !ids.is_empty() {
+            filter.insert("mentions", doc! { "$in": ids });
+        }
+    }
+    if query.pinned_only {
+        // Matches either the new `flags` bitfield or a legacy document that
+        // still only has the `is_pinned` bool it was migrated from.
+        filter.insert(
+            "$or",
+            vec![
+                doc! { "is_pinned": true },
+                doc! { "flags": { "$bitsAllSet": MessageFlags::PINNED.bits() as i32 } },
+            ],
+        );
+    }
+    if let Some(has) = &query.has {
+        for flag in has.split(',').filter(|s| !s.is_empty()) {
+            match flag {
+                "attachment" => {
+                    filter.insert("attachments.0", doc! { "$exists": true });
+                }
+                "link" => {
+                    filter.insert("content", doc! { "$regex": "https?://", "$options": "i" });
+                }
+                "poll" => {
+                    filter.insert("message_type", "poll");
+                }
+                _ => {}
+            }
+        }
+    }
+    let mut created_at_range = doc! {};
+    if let Some(after) = query.created_after {
+        created_at_range.insert("$gte", after);
+    }
+    if let Some(before) = query.created_before {
+        created_at_range.insert("$lte", before);
+    }
+    if !created_at_range.is_empty() {
+        filter.insert("created_at", created_at_range);
+    }
+
+    let options = FindOptions::builder().sort(doc! { "created_at": -1 }).limit(limit).build();
+
+    let hits: Vec<Message> = match collection.find(filter.clone(), options).await {
+        Ok(cursor) => cursor.try_collect().await.unwrap_or_default(),
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+    };
+    let total_hits = collection.count_documents(filter, None).await.unwrap_or(0);
+
+    let mut context = Vec::with_capacity(hits.len());
+    for message in &hits {
+        let window = if context_window > 0 {
+            fetch_context(&collection, message, context_window).await
+        } else {
+            vec![]
+        };
+        context.push(window);
+    }
+
+    HttpResponse::Ok().json(MessagesResponse {
+        has_more: hits.len() as i64 == limit,
+        cursor: hits.last().and_then(|m| m.id.map(|id| id.to_hex())),
+        messages: hits,
+        total_hits: Some(total_hits),
+        context: Some(context),
+    })
+}
+
+/// Fetches up to `window` messages immediately before and after `message` in
+/// the same channel, ordered chronologically, to give a search hit context.
+async fn fetch_context(
+    collection: &mongodb::Collection<Message>,
+    message: &Message,
+    window: i64,
+) -> Vec<Message> {
+    let before_options = FindOptions::builder().sort(doc! { "created_at": -1 }).limit(window).build();
+    let mut before: Vec<Message> = match collection
+        .find(
+            doc! {
+                "channel_id": &message.channel_id,
+                "deleted_at": null,
+                "created_at": { "$lt": message.created_at },
+            },
+            before_options,
+        )
+        .await
+    {
+        Ok(cursor) => cursor.try_collect().await.unwrap_or_default(),
+        Err(_) => vec![],
+    };
+    before.reverse();
+
+    let after_options = FindOptions::builder().sort(doc! { "created_at": 1 }).limit(window).build();
+    let after: Vec<Message> = match collection
+        .find(
+            doc! {
+                "channel_id": &message.channel_id,
+                "deleted_at": null,
+                "created_at": { "$gt": message.created_at },
+            },
+            after_options,
+        )
+        .await
+    {
+        Ok(cursor) => cursor.try_collect().await.unwrap_or_default(),
+        Err(_) => vec![],
+    };
+
+    before.extend(after);
+    before
+}
+
 pub async fn update_message(
     state: web::Data<AppState>,
     path: web::Path<String>,
@@ -115,7 +508,12 @@ pub async fn update_message(
     }
 
     match collection.update_one(doc! { "_id": object_id }, update, None).await {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({"message": "Updated"})),
+        Ok(_) => {
+            if let Ok(Some(message)) = collection.find_one(doc! { "_id": object_id }, None).await {
+                crate::gateway::publish(&state, Event::MessageUpdate { payload: message }).await;
+            }
+            HttpResponse::Ok().json(serde_json::json!({"message": "Updated"}))
+        }
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
     }
 }
@@ -132,9 +530,20 @@ pub async fn delete_message(
         Err(_) => return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid ID"})),
     };
 
-    let update = doc! { "$set": { "deleted_at": Utc::now() } };
+    let deleted_at = Utc::now();
+    let existing = collection.find_one(doc! { "_id": object_id }, None).await.unwrap_or(None);
+    let update = doc! { "$set": { "deleted_at": deleted_at } };
     match collection.update_one(doc! { "_id": object_id }, update, None).await {
-        Ok(_) => HttpResponse::NoContent().finish(),
+        Ok(_) => {
+            if let Some(message) = existing {
+                crate::gateway::publish(
+                    &state,
+                    Event::MessageDelete { channel_id: message.channel_id, message_id: id, deleted_at },
+                )
+                .await;
+            }
+            HttpResponse::NoContent().finish()
+        }
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
     }
 }
@@ -163,7 +572,21 @@ pub async fn add_reaction(
     };
 
     match collection.update_one(doc! { "_id": object_id }, update, None).await {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({"message": "Reaction added"})),
+        Ok(_) => {
+            if let Ok(Some(message)) = collection.find_one(doc! { "_id": object_id }, None).await {
+                crate::gateway::publish(
+                    &state,
+                    Event::ReactionAdd {
+                        channel_id: message.channel_id,
+                        message_id: id,
+                        emoji: body.emoji.clone(),
+                        user_id: body.user_id.clone(),
+                    },
+                )
+                .await;
+            }
+            HttpResponse::Ok().json(serde_json::json!({"message": "Reaction added"}))
+        }
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
     }
 }
@@ -171,6 +594,7 @@ pub async fn add_reaction(
 pub async fn remove_reaction(
     state: web::Data<AppState>,
     path: web::Path<(String, String)>,
+    query: web::Query<RemoveReactionQuery>,
 ) -> HttpResponse {
     let (id, emoji) = path.into_inner();
     let collection = state.db.collection::<Message>("messages");
@@ -180,14 +604,29 @@ pub async fn remove_reaction(
         Err(_) => return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid ID"})),
     };
 
+    let existing = collection.find_one(doc! { "_id": object_id }, None).await.unwrap_or(None);
     let update = doc! {
         "$pull": {
-            "reactions": { "emoji": emoji }
+            "reactions": { "emoji": &emoji }
         }
     };
 
     match collection.update_one(doc! { "_id": object_id }, update, None).await {
-        Ok(_) => HttpResponse::NoContent().finish(),
+        Ok(_) => {
+            if let Some(message) = existing {
+                crate::gateway::publish(
+                    &state,
+                    Event::ReactionRemove {
+                        channel_id: message.channel_id,
+                        message_id: id,
+                        emoji,
+                        user_id: query.user_id.clone().unwrap_or_default(),
+                    },
+                )
+                .await;
+            }
+            HttpResponse::NoContent().finish()
+        }
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
     }
 }
@@ -204,7 +643,11 @@ pub async fn pin_message(
         Err(_) => return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid ID"})),
     };
 
-    match collection.update_one(doc! { "_id": object_id }, doc! { "$set": { "is_pinned": true } }, None).await {
+    let update = doc! {
+        "$bit": { "flags": { "or": MessageFlags::PINNED.bits() as i32 } },
+        "$unset": { "is_pinned": "" },
+    };
+    match collection.update_one(doc! { "_id": object_id }, update, None).await {
         Ok(_) => HttpResponse::Ok().json(serde_json::json!({"message": "Pinned"})),
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
     }
@@ -222,12 +665,191 @@ pub async fn unpin_message(
         Err(_) => return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid ID"})),
     };
 
-    match collection.update_one(doc! { "_id": object_id }, doc! { "$set": { "is_pinned": false } }, None).await {
+    let update = doc! {
+        "$bit": { "flags": { "and": !(MessageFlags::PINNED.bits() as i32) } },
+        "$unset": { "is_pinned": "" },
+    };
+    match collection.update_one(doc! { "_id": object_id }, update, None).await {
         Ok(_) => HttpResponse::Ok().json(serde_json::json!({"message": "Unpinned"})),
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
     }
 }
 
+/// Creates a poll as a new `Poll`-type message. Each option text is assigned
+/// a short generated id so votes can reference options without relying on
+/// array position, which would shift if options were ever edited.
+pub async fn create_poll(
+    state: web::Data<AppState>,
+    body: web::Json<CreatePollRequest>,
+) -> HttpResponse {
+    if body.options.len() < 2 {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "a poll needs at least two options"}));
+    }
+
+    let collection = state.db.collection::<Message>("messages");
+    let poll = Poll {
+        question: body.question.clone(),
+        options: body
+            .options
+            .iter()
+            .map(|text| PollOption {
+                id: uuid::Uuid::new_v4().to_string(),
+                text: text.clone(),
+                emoji: None,
+                vote_count: 0,
+            })
+            .collect(),
+        allow_multiselect: body.allow_multiselect,
+        expires_at: body.expires_at,
+        voters: vec![],
+    };
+
+    let message = Message {
+        id: None,
+        channel_id: body.channel_id.clone(),
+        user_id: body.user_id.clone(),
+        content: body.question.clone(),
+        thread_id: None,
+        parent_message_id: None,
+        message_type: MessageType::Poll,
+        attachments: vec![],
+        mentions: vec![],
+        reactions: vec![],
+        edited_at: None,
+        deleted_at: None,
+        flags: MessageFlags::empty(),
+        poll: Some(poll),
+        remote_id: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+
+    match collection.insert_one(message.clone(), None).await {
+        Ok(result) => {
+            let mut msg = message;
+            msg.id = result.inserted_id.as_object_id();
+            crate::gateway::publish(&state, Event::MessageCreate { payload: msg.clone() }).await;
+            crate::federation::fan_out_create(&state, &msg).await;
+            HttpResponse::Created().json(msg)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": format!("Failed to create poll: {}", e)
+        })),
+    }
+}
+
+/// Casts (or replaces) the caller's ballot. Rejects votes after `expires_at`
+/// and enforces single- vs multi-select before persisting, then returns the
+/// updated aggregate tallies — never the raw per-user ballots.
+pub async fn cast_vote(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<CastVoteRequest>,
+) -> HttpResponse {
+    let collection = state.db.collection::<Message>("messages");
+    let id = path.into_inner();
+
+    let object_id = match ObjectId::parse_str(&id) {
+        Ok(oid) => oid,
+        Err(_) => return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid ID"})),
+    };
+
+    let Ok(Some(mut message)) = collection.find_one(doc! { "_id": object_id, "deleted_at": null }, None).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({"error": "Message not found"}));
+    };
+    let Some(poll) = &mut message.poll else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "Message is not a poll"}));
+    };
+
+    if poll.is_expired(Utc::now()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "Poll has expired"}));
+    }
+    if body.option_ids.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "At least one option must be selected"}));
+    }
+    if !poll.allow_multiselect && body.option_ids.len() > 1 {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "This poll only allows a single selection"}));
+    }
+    let valid_ids: std::collections::HashSet<&str> = poll.options.iter().map(|o| o.id.as_str()).collect();
+    if body.option_ids.iter().any(|id| !valid_ids.contains(id.as_str())) {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "Unknown option id"}));
+    }
+
+    poll.voters.retain(|v| v.user_id != body.user_id);
+    poll.voters.push(PollVote { user_id: body.user_id.clone(), option_ids: body.option_ids.clone() });
+    recompute_vote_counts(poll);
+    let results = poll.results();
+
+    let update = doc! { "$set": { "poll": bson::to_bson(&message.poll).unwrap_or(bson::Bson::Null), "updated_at": Utc::now() } };
+    match collection.update_one(doc! { "_id": object_id }, update, None).await {
+        Ok(_) => HttpResponse::Ok().json(results),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+    }
+}
+
+/// Withdraws the caller's existing ballot, if any, and returns the updated
+/// tallies.
+pub async fn retract_vote(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<RetractVoteQuery>,
+) -> HttpResponse {
+    let collection = state.db.collection::<Message>("messages");
+    let id = path.into_inner();
+    let user_id = query.user_id.clone().unwrap_or_default();
+
+    let object_id = match ObjectId::parse_str(&id) {
+        Ok(oid) => oid,
+        Err(_) => return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid ID"})),
+    };
+
+    let Ok(Some(mut message)) = collection.find_one(doc! { "_id": object_id, "deleted_at": null }, None).await else {
+        return HttpResponse::NotFound().json(serde_json::json!({"error": "Message not found"}));
+    };
+    let Some(poll) = &mut message.poll else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "Message is not a poll"}));
+    };
+
+    poll.voters.retain(|v| v.user_id != user_id);
+    recompute_vote_counts(poll);
+    let results = poll.results();
+
+    let update = doc! { "$set": { "poll": bson::to_bson(&message.poll).unwrap_or(bson::Bson::Null), "updated_at": Utc::now() } };
+    match collection.update_one(doc! { "_id": object_id }, update, None).await {
+        Ok(_) => HttpResponse::Ok().json(results),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+    }
+}
+
+/// Returns the current aggregate tallies without mutating anything.
+pub async fn get_poll_results(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let collection = state.db.collection::<Message>("messages");
+    let id = path.into_inner();
+
+    let object_id = match ObjectId::parse_str(&id) {
+        Ok(oid) => oid,
+        Err(_) => return HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid ID"})),
+    };
+
+    match collection.find_one(doc! { "_id": object_id, "deleted_at": null }, None).await {
+        Ok(Some(message)) => match &message.poll {
+            Some(poll) => HttpResponse::Ok().json(poll.results()),
+            None => HttpResponse::BadRequest().json(serde_json::json!({"error": "Message is not a poll"})),
+        },
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({"error": "Message not found"})),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+    }
+}
+
+fn recompute_vote_counts(poll: &mut Poll) {
+    for option in &mut poll.options {
+        option.vote_count = poll.voters.iter().filter(|v| v.option_ids.contains(&option.id)).count() as u32;
+    }
+}
+
 pub async fn get_channel_messages(
     state: web::Data<AppState>,
     path: web::Path<String>,
@@ -250,6 +872,8 @@ pub async fn get_channel_messages(
                 has_more: messages.len() as i64 == limit,
                 cursor: messages.last().and_then(|m| m.id.map(|id| id.to_hex())),
                 messages,
+                total_hits: None,
+                context: None,
             })
         }
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
@@ -278,6 +902,8 @@ pub async fn get_thread_messages(
                 has_more: messages.len() as i64 == limit,
                 cursor: None,
                 messages,
+                total_hits: None,
+                context: None,
             })
         }
         Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
@@ -1,11 +1,23 @@
 use actix_web::web;
 use super::handlers;
+use crate::federation;
+use crate::gateway;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(
+    cfg.route("/gateway/ws", web::get().to(gateway::ws_handler))
+    .service(
+        web::scope("/federation/channels/{channel_id}")
+            .route("/actor", web::get().to(federation::get_actor))
+            .route("/outbox", web::get().to(federation::get_outbox))
+            .route("/inbox", web::post().to(federation::post_inbox))
+    )
+    .service(
         web::scope("/messages")
             .route("", web::post().to(handlers::create_message))
             .route("", web::get().to(handlers::list_messages))
+            .route("/search", web::get().to(handlers::search_messages))
+            .route("/attachments", web::post().to(handlers::create_message_with_attachment))
+            .route("/polls", web::post().to(handlers::create_poll))
             .route("/{id}", web::get().to(handlers::get_message))
             .route("/{id}", web::put().to(handlers::update_message))
             .route("/{id}", web::delete().to(handlers::delete_message))
@@ -13,6 +25,9 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .route("/{id}/reactions/{emoji}", web::delete().to(handlers::remove_reaction))
             .route("/{id}/pin", web::post().to(handlers::pin_message))
             .route("/{id}/unpin", web::post().to(handlers::unpin_message))
+            .route("/{id}/poll/votes", web::post().to(handlers::cast_vote))
+            .route("/{id}/poll/votes", web::delete().to(handlers::retract_vote))
+            .route("/{id}/poll/results", web::get().to(handlers::get_poll_results))
     )
     .service(
         web::scope("/channels/{channel_id}/messages")
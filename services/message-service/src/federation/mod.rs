@@ -0,0 +1,576 @@
+//! ActivityPub federation: exposes an `Actor` document per channel, accepts
+//! signed `Create`/`Update`/`Delete` activities at that channel's inbox, and
+//! delivers locally-created messages to remote followers as HTTP-signed
+//! `Create{Note}` activities. Modeled on the actor-per-resource approach
+//! used by other Fediverse servers rather than introducing a full user
+//! identity system of our own.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use bson::doc;
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::sign::{Signer, Verifier};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{Attachment, Message, MessageFlags, MessageType};
+use crate::AppState;
+
+const CONTEXT: &str = "https://www.w3.org/ns/activitystreams";
+
+/// RSA keypair used to sign outgoing activities on behalf of a channel,
+/// generated lazily on first use and cached in Mongo so it's stable across
+/// restarts and other instances behind the same deployment.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ActorKey {
+    channel_id: String,
+    private_key_pem: String,
+    public_key_pem: String,
+}
+
+/// A remote server that has `Follow`ed a channel, recorded so locally
+/// created messages know where to be delivered.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Follower {
+    channel_id: String,
+    actor_id: String,
+    inbox: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Actor {
+    #[serde(rename = "@context")]
+    context: &'static str,
+    #[serde(rename = "type")]
+    actor_type: &'static str,
+    id: String,
+    inbox: String,
+    outbox: String,
+    #[serde(rename = "preferredUsername")]
+    preferred_username: String,
+    #[serde(rename = "publicKey")]
+    public_key: PublicKey,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PublicKey {
+    id: String,
+    owner: String,
+    #[serde(rename = "publicKeyPem")]
+    public_key_pem: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Activity {
+    #[serde(rename = "@context", skip_serializing_if = "Option::is_none")]
+    context: Option<String>,
+    #[serde(rename = "type")]
+    activity_type: String,
+    id: String,
+    actor: String,
+    object: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    published: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Note {
+    #[serde(rename = "type")]
+    object_type: String,
+    id: String,
+    #[serde(rename = "attributedTo")]
+    attributed_to: String,
+    content: String,
+    published: DateTime<Utc>,
+    #[serde(rename = "inReplyTo", skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<String>,
+    #[serde(default)]
+    attachment: Vec<NoteAttachment>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct NoteAttachment {
+    #[serde(rename = "type")]
+    attachment_type: String,
+    #[serde(rename = "mediaType")]
+    media_type: String,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+fn actor_url(state: &AppState, channel_id: &str) -> String {
+    format!("{}/api/v1/federation/channels/{channel_id}/actor", state.federation_base_url)
+}
+
+fn inbox_url(state: &AppState, channel_id: &str) -> String {
+    format!("{}/api/v1/federation/channels/{channel_id}/inbox", state.federation_base_url)
+}
+
+fn outbox_url(state: &AppState, channel_id: &str) -> String {
+    format!("{}/api/v1/federation/channels/{channel_id}/outbox", state.federation_base_url)
+}
+
+fn key_id(state: &AppState, channel_id: &str) -> String {
+    format!("{}#main-key", actor_url(state, channel_id))
+}
+
+/// Returns the channel's signing keypair, generating and persisting a fresh
+/// 2048-bit RSA key the first time this channel federates.
+async fn get_or_create_key(state: &AppState, channel_id: &str) -> Option<ActorKey> {
+    let collection = state.db.collection::<ActorKey>("federation_keys");
+    if let Ok(Some(key)) = collection.find_one(doc! { "channel_id": channel_id }, None).await {
+        return Some(key);
+    }
+
+    let rsa = Rsa::generate(2048).ok()?;
+    let private_key_pem = String::from_utf8(rsa.private_key_to_pem().ok()?).ok()?;
+    let public_key_pem = String::from_utf8(rsa.public_key_to_pem().ok()?).ok()?;
+    let key = ActorKey { channel_id: channel_id.to_string(), private_key_pem, public_key_pem };
+
+    let _ = collection.insert_one(key.clone(), None).await;
+    Some(key)
+}
+
+/// `GET /federation/channels/{channel_id}/actor`
+pub async fn get_actor(state: web::Data<AppState>, path: web::Path<String>) -> HttpResponse {
+    let channel_id = path.into_inner();
+    let Some(key) = get_or_create_key(&state, &channel_id).await else {
+        return HttpResponse::InternalServerError().json(serde_json::json!({"error": "failed to provision signing key"}));
+    };
+
+    HttpResponse::Ok().json(Actor {
+        context: CONTEXT,
+        actor_type: "Group",
+        id: actor_url(&state, &channel_id),
+        inbox: inbox_url(&state, &channel_id),
+        outbox: outbox_url(&state, &channel_id),
+        preferred_username: channel_id.clone(),
+        public_key: PublicKey {
+            id: key_id(&state, &channel_id),
+            owner: actor_url(&state, &channel_id),
+            public_key_pem: key.public_key_pem,
+        },
+    })
+}
+
+/// `GET /federation/channels/{channel_id}/outbox` — the channel's recent
+/// messages as an `OrderedCollection` of `Create{Note}` activities.
+pub async fn get_outbox(state: web::Data<AppState>, path: web::Path<String>) -> HttpResponse {
+    let channel_id = path.into_inner();
+    let collection = state.db.collection::<Message>("messages");
+
+    let messages: Vec<Message> = match collection
+        .find(
+            doc! { "channel_id": &channel_id, "deleted_at": null },
+            mongodb::options::FindOptions::builder().sort(doc! { "created_at": -1 }).limit(20).build(),
+        )
+        .await
+    {
+        Ok(cursor) => cursor.try_collect().await.unwrap_or_default(),
+        Err(_) => vec![],
+    };
+
+    let items: Vec<serde_json::Value> = messages
+        .iter()
+        .map(|message| serde_json::to_value(create_activity(&state, message)).unwrap_or(serde_json::Value::Null))
+        .collect();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "@context": CONTEXT,
+        "type": "OrderedCollection",
+        "totalItems": items.len(),
+        "orderedItems": items,
+    }))
+}
+
+/// `POST /federation/channels/{channel_id}/inbox` — accepts `Follow`,
+/// `Create`, `Update` and `Delete` activities from remote servers. Every
+/// request must carry a valid HTTP signature from the actor it claims to be.
+pub async fn post_inbox(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let channel_id = path.into_inner();
+
+    let activity: Activity = match serde_json::from_slice(&body) {
+        Ok(activity) => activity,
+        Err(_) => return HttpResponse::BadRequest().json(serde_json::json!({"error": "invalid activity"})),
+    };
+
+    if !verify_signature(&state, &req, &activity.actor).await {
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "invalid or missing signature"}));
+    }
+
+    match activity.activity_type.as_str() {
+        "Follow" => handle_follow(&state, &channel_id, &activity).await,
+        "Create" | "Update" => handle_create_or_update(&state, &channel_id, &activity).await,
+        "Delete" => handle_delete(&state, &activity).await,
+        _ => HttpResponse::Accepted().finish(),
+    }
+}
+
+async fn handle_follow(state: &AppState, channel_id: &str, activity: &Activity) -> HttpResponse {
+    let Some(remote_actor) = fetch_actor(state, &activity.actor).await else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "could not resolve follower actor"}));
+    };
+
+    let follower = Follower {
+        channel_id: channel_id.to_string(),
+        actor_id: activity.actor.clone(),
+        inbox: remote_actor.inbox.clone(),
+    };
+    let collection = state.db.collection::<Follower>("federation_followers");
+    let _ = collection
+        .update_one(
+            doc! { "channel_id": channel_id, "actor_id": &activity.actor },
+            doc! { "$set": { "inbox": &follower.inbox } },
+            mongodb::options::UpdateOptions::builder().upsert(true).build(),
+        )
+        .await;
+
+    if let Some(key) = get_or_create_key(state, channel_id).await {
+        let accept = Activity {
+            context: Some(CONTEXT.to_string()),
+            activity_type: "Accept".to_string(),
+            id: format!("{}#accept-{}", actor_url(state, channel_id), uuid::Uuid::new_v4()),
+            actor: actor_url(state, channel_id),
+            object: serde_json::to_value(activity).unwrap_or(serde_json::Value::Null),
+            published: Some(Utc::now()),
+        };
+        deliver(state, &follower.inbox, &accept, channel_id, &key).await;
+    }
+
+    HttpResponse::Accepted().finish()
+}
+
+async fn handle_create_or_update(state: &AppState, channel_id: &str, activity: &Activity) -> HttpResponse {
+    let Ok(note) = serde_json::from_value::<Note>(activity.object.clone()) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "expected a Note object"}));
+    };
+
+    let collection = state.db.collection::<Message>("messages");
+
+    // A signature only proves the request came from `activity.actor`; it
+    // says nothing about whether that actor is the one who created the
+    // message being updated. Without this check, any followed remote actor
+    // could overwrite another actor's note by replaying its `remote_id`.
+    match collection.find_one(doc! { "remote_id": &note.id }, None).await {
+        Ok(Some(existing)) if existing.user_id != activity.actor => {
+            return HttpResponse::Forbidden().json(serde_json::json!({"error": "actor does not own this message"}));
+        }
+        Ok(_) => {}
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+    }
+
+    let message = note_to_message(channel_id, &activity.actor, &note);
+    let update = bson::to_document(&message).unwrap_or_default();
+    match collection
+        .update_one(
+            doc! { "remote_id": &note.id },
+            doc! { "$set": update },
+            mongodb::options::UpdateOptions::builder().upsert(true).build(),
+        )
+        .await
+    {
+        Ok(_) => {
+            crate::gateway::publish(state, crate::models::Event::MessageCreate { payload: message }).await;
+            HttpResponse::Accepted().finish()
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+    }
+}
+
+async fn handle_delete(state: &AppState, activity: &Activity) -> HttpResponse {
+    let remote_id = match &activity.object {
+        serde_json::Value::String(id) => id.clone(),
+        serde_json::Value::Object(obj) => obj.get("id").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+        _ => return HttpResponse::BadRequest().json(serde_json::json!({"error": "invalid Delete object"})),
+    };
+
+    let collection = state.db.collection::<Message>("messages");
+
+    // Same ownership requirement as `handle_create_or_update`: a valid
+    // signature only proves who sent the Delete, not that they're allowed
+    // to delete this particular message.
+    match collection.find_one(doc! { "remote_id": &remote_id }, None).await {
+        Ok(Some(existing)) if existing.user_id != activity.actor => {
+            return HttpResponse::Forbidden().json(serde_json::json!({"error": "actor does not own this message"}));
+        }
+        Ok(None) => return HttpResponse::NotFound().json(serde_json::json!({"error": "message not found"})),
+        Ok(_) => {}
+        Err(e) => return HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+    }
+
+    let deleted_at = Utc::now();
+    match collection
+        .update_one(doc! { "remote_id": &remote_id }, doc! { "$set": { "deleted_at": deleted_at } }, None)
+        .await
+    {
+        Ok(_) => HttpResponse::Accepted().finish(),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+    }
+}
+
+fn note_to_message(channel_id: &str, actor: &str, note: &Note) -> Message {
+    Message {
+        id: None,
+        channel_id: channel_id.to_string(),
+        user_id: actor.to_string(),
+        content: note.content.clone(),
+        thread_id: None,
+        parent_message_id: note.in_reply_to.clone(),
+        message_type: MessageType::Text,
+        attachments: note.attachment.iter().map(note_attachment_to_attachment).collect(),
+        mentions: vec![],
+        reactions: vec![],
+        edited_at: None,
+        deleted_at: None,
+        flags: MessageFlags::empty(),
+        poll: None,
+        remote_id: Some(note.id.clone()),
+        created_at: note.published,
+        updated_at: note.published,
+    }
+}
+
+fn note_attachment_to_attachment(attachment: &NoteAttachment) -> Attachment {
+    Attachment {
+        id: uuid::Uuid::new_v4().to_string(),
+        file_type: attachment.media_type.clone(),
+        file_name: attachment.name.clone().unwrap_or_else(|| "attachment".to_string()),
+        file_size: 0,
+        url: attachment.url.clone(),
+        thumbnail_url: None,
+        width: None,
+        height: None,
+        duration_secs: None,
+        waveform: None,
+    }
+}
+
+fn message_to_note(state: &AppState, message: &Message) -> Note {
+    Note {
+        object_type: "Note".to_string(),
+        id: format!(
+            "{}#{}",
+            actor_url(state, &message.channel_id),
+            message.id.map(|id| id.to_hex()).unwrap_or_default()
+        ),
+        attributed_to: message.user_id.clone(),
+        content: message.content.clone(),
+        published: message.created_at,
+        in_reply_to: message.parent_message_id.clone(),
+        attachment: message
+            .attachments
+            .iter()
+            .map(|a| NoteAttachment {
+                attachment_type: "Document".to_string(),
+                media_type: a.file_type.clone(),
+                url: a.url.clone(),
+                name: Some(a.file_name.clone()),
+            })
+            .collect(),
+    }
+}
+
+fn create_activity(state: &AppState, message: &Message) -> Activity {
+    Activity {
+        context: Some(CONTEXT.to_string()),
+        activity_type: "Create".to_string(),
+        id: format!("{}/activities/{}", actor_url(state, &message.channel_id), uuid::Uuid::new_v4()),
+        actor: actor_url(state, &message.channel_id),
+        object: serde_json::to_value(message_to_note(state, message)).unwrap_or(serde_json::Value::Null),
+        published: Some(message.created_at),
+    }
+}
+
+/// Delivers a locally created message to every remote server following its
+/// channel, as a signed `Create{Note}` activity. Delivery failures are logged
+/// and never fail the caller's write, but deliveries are sent one after
+/// another and the caller waits until all of them have finished.
+pub async fn fan_out_create(state: &AppState, message: &Message) {
+    let Some(key) = get_or_create_key(state, &message.channel_id).await else { return };
+    let activity = create_activity(state, message);
+
+    let collection = state.db.collection::<Follower>("federation_followers");
+    let followers: Vec<Follower> = match collection.find(doc! { "channel_id": &message.channel_id }, None).await {
+        Ok(cursor) => cursor.try_collect().await.unwrap_or_default(),
+        Err(_) => vec![],
+    };
+
+    for follower in followers {
+        deliver(state, &follower.inbox, &activity, &message.channel_id, &key).await;
+    }
+}
+
+/// HTTP-signs `activity` with the channel's private key (draft-cavage HTTP
+/// Signatures, as used across the Fediverse) and POSTs it to `inbox`.
+async fn deliver(state: &AppState, inbox: &str, activity: &Activity, channel_id: &str, key: &ActorKey) {
+    let Ok(body) = serde_json::to_vec(activity) else { return };
+    let Ok(url) = reqwest::Url::parse(inbox) else { return };
+    let Some(host) = url.host_str() else { return };
+
+    let digest = format!("SHA-256={}", openssl_sha256_base64(&body));
+    let date = Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let request_target = format!("post {}", url.path());
+
+    let signing_string = format!(
+        "(request-target): {request_target}\nhost: {host}\ndate: {date}\ndigest: {digest}"
+    );
+    let Some(signature) = sign(&key.private_key_pem, &signing_string) else { return };
+    let header = format!(
+        "keyId=\"{}\",algorithm=\"rsa-sha256\",headers=\"(request-target) host date digest\",signature=\"{}\"",
+        key_id(state, channel_id),
+        signature
+    );
+
+    let result = state
+        .http
+        .post(inbox)
+        .header("Host", host)
+        .header("Date", date)
+        .header("Digest", digest)
+        .header("Signature", header)
+        .header("Content-Type", "application/activity+json")
+        .body(body)
+        .send()
+        .await;
+
+    if let Err(e) = result {
+        tracing::warn!("federation: delivery to {inbox} failed: {e}");
+    }
+}
+
+fn sign(private_key_pem: &str, signing_string: &str) -> Option<String> {
+    let pkey = PKey::private_key_from_pem(private_key_pem.as_bytes()).ok()?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &pkey).ok()?;
+    signer.update(signing_string.as_bytes()).ok()?;
+    let signature = signer.sign_to_vec().ok()?;
+    Some(base64_encode(&signature))
+}
+
+fn openssl_sha256_base64(body: &[u8]) -> String {
+    let digest = openssl::hash::hash(MessageDigest::sha256(), body).unwrap_or_default();
+    base64_encode(&digest)
+}
+
+/// Verifies the inbound request's `Signature` header against the public key
+/// published by the activity's claimed `actor`, fetching that actor's
+/// document over HTTP on every request (actor documents are not cached).
+async fn verify_signature(state: &AppState, req: &HttpRequest, actor: &str) -> bool {
+    let Some(signature_header) = req.headers().get("signature").and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let params = parse_signature_header(signature_header);
+    let (Some(signature_b64), Some(headers_list)) = (params.get("signature"), params.get("headers")) else {
+        return false;
+    };
+
+    let Some(remote_actor) = fetch_actor(state, actor).await else { return false };
+
+    let signing_string = headers_list
+        .split(' ')
+        .map(|header_name| {
+            if header_name == "(request-target)" {
+                format!("(request-target): {} {}", req.method().as_str().to_lowercase(), req.uri().path())
+            } else {
+                let value = req.headers().get(header_name).and_then(|v| v.to_str().ok()).unwrap_or("");
+                format!("{header_name}: {value}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let Ok(signature) = base64_decode(signature_b64) else { return false };
+    let Ok(pkey) = PKey::public_key_from_pem(remote_actor.public_key.public_key_pem.as_bytes()) else { return false };
+    let Ok(mut verifier) = Verifier::new(MessageDigest::sha256(), &pkey) else { return false };
+    verifier.update(signing_string.as_bytes()).is_ok() && verifier.verify(&signature).unwrap_or(false)
+}
+
+fn parse_signature_header(header: &str) -> std::collections::HashMap<String, String> {
+    header
+        .split(',')
+        .filter_map(|part| {
+            let (key, value) = part.split_once('=')?;
+            Some((key.trim().to_string(), value.trim().trim_matches('"').to_string()))
+        })
+        .collect()
+}
+
+async fn fetch_actor(state: &AppState, actor_id: &str) -> Option<Actor> {
+    let response = state
+        .http
+        .get(actor_id)
+        .header("Accept", "application/activity+json")
+        .send()
+        .await
+        .ok()?;
+    response.json::<Actor>().await.ok()
+}
+
+impl<'de> Deserialize<'de> for Actor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Raw {
+            id: String,
+            inbox: String,
+            #[serde(default)]
+            outbox: String,
+            #[serde(rename = "preferredUsername", default)]
+            preferred_username: String,
+            #[serde(rename = "publicKey")]
+            public_key: PublicKey,
+        }
+        let raw = Raw::deserialize(deserializer)?;
+        Ok(Actor {
+            context: CONTEXT,
+            actor_type: "Group",
+            id: raw.id,
+            inbox: raw.inbox,
+            outbox: raw.outbox,
+            preferred_username: raw.preferred_username,
+            public_key: raw.public_key,
+        })
+    }
+}
+
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let b0 = chunk[0];
+        let b1 = *chunk.get(1).unwrap_or(&0);
+        let b2 = *chunk.get(2).unwrap_or(&0);
+        out.push(BASE64_CHARS[(b0 >> 2) as usize] as char);
+        out.push(BASE64_CHARS[(((b0 & 0x03) << 4) | (b1 >> 4)) as usize] as char);
+        out.push(if chunk.len() > 1 { BASE64_CHARS[(((b1 & 0x0f) << 2) | (b2 >> 6)) as usize] as char } else { '=' });
+        out.push(if chunk.len() > 2 { BASE64_CHARS[(b2 & 0x3f) as usize] as char } else { '=' });
+    }
+    out
+}
+
+fn base64_decode(s: &str) -> Result<Vec<u8>, ()> {
+    let lookup = |c: u8| -> Option<u8> { BASE64_CHARS.iter().position(|&x| x == c).map(|p| p as u8) };
+    let clean: Vec<u8> = s.bytes().filter(|&b| b != b'=').collect();
+    let mut out = Vec::new();
+    for chunk in clean.chunks(4) {
+        let vals: Vec<u8> = chunk.iter().map(|&b| lookup(b).ok_or(())).collect::<Result<_, _>>()?;
+        out.push((vals[0] << 2) | (vals.get(1).unwrap_or(&0) >> 4));
+        if vals.len() > 2 {
+            out.push((vals[1] << 4) | (vals[2] >> 2));
+        }
+        if vals.len() > 3 {
+            out.push((vals[2] << 6) | vals[3]);
+        }
+    }
+    Ok(out)
+}
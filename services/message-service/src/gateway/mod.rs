@@ -0,0 +1,168 @@
+//! Real-time event gateway: a WebSocket endpoint that fans out `Event`
+//! frames to subscribers of the relevant `channel_id`. Events are published
+//! on an in-process broadcast channel by the same handlers that persist to
+//! MongoDB, and mirrored through Redis pub/sub (when configured) so multiple
+//! service instances stay in sync.
+
+use actix::{Actor, ActorContext, AsyncContext, StreamHandler};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use std::collections::HashSet;
+use tokio::sync::broadcast;
+
+use crate::models::Event;
+use crate::AppState;
+
+/// Local fan-out bus. Every gateway session subscribes its own receiver;
+/// `capacity` bounds how far a slow consumer can lag before it starts
+/// missing events rather than unbounded memory growth.
+pub fn new_bus() -> broadcast::Sender<Event> {
+    let (tx, _rx) = broadcast::channel(1024);
+    tx
+}
+
+/// Publishes an event to the `message_events` pub/sub channel when Redis is
+/// configured; `run_redis_relay` on every instance, this one included, then
+/// rebroadcasts it to locally-connected clients. Without Redis, or if the
+/// publish fails, the event goes straight onto the local bus instead.
+pub async fn publish(state: &AppState, event: Event) {
+    if let Some(redis) = &state.redis {
+        if let Ok(payload) = serde_json::to_string(&event) {
+            if let Ok(mut conn) = redis.get_multiplexed_async_connection().await {
+                let result: redis::RedisResult<()> =
+                    redis::cmd("PUBLISH").arg("message_events").arg(payload).query_async(&mut conn).await;
+                match result {
+                    Ok(()) => return,
+                    Err(e) => tracing::warn!("gateway: failed to publish event to redis: {e}"),
+                }
+            }
+        }
+    }
+
+    let _ = state.events.send(event);
+}
+
+/// Background task that relays events published on Redis pub/sub, by any
+/// instance including this one, into this instance's local broadcast bus.
+pub async fn run_redis_relay(state: web::Data<AppState>) {
+    let Some(redis) = state.redis.clone() else { return };
+
+    loop {
+        let mut pubsub = match redis.get_async_pubsub().await {
+            Ok(pubsub) => pubsub,
+            Err(e) => {
+                tracing::warn!("gateway: redis pubsub connection failed: {e}");
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        if let Err(e) = pubsub.subscribe("message_events").await {
+            tracing::warn!("gateway: failed to subscribe to message_events: {e}");
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            continue;
+        }
+
+        let mut stream = pubsub.on_message();
+        use futures::StreamExt;
+        while let Some(msg) = stream.next().await {
+            let payload: String = match msg.get_payload() {
+                Ok(p) => p,
+                Err(_) => continue,
+            };
+            if let Ok(event) = serde_json::from_str::<Event>(&payload) {
+                let _ = state.events.send(event);
+            }
+        }
+    }
+}
+
+/// Upgrades the connection to a WebSocket and hands it off to a
+/// `GatewaySession` actor that owns this client's channel subscriptions.
+pub async fn ws_handler(
+    req: HttpRequest,
+    stream: web::Payload,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let session =
+        GatewaySession { subscriptions: HashSet::new(), events: state.events.subscribe(), state: state.clone() };
+    ws::start(session, &req, stream)
+}
+
+struct GatewaySession {
+    subscriptions: HashSet<String>,
+    events: broadcast::Receiver<Event>,
+    state: web::Data<AppState>,
+}
+
+/// Client -> server control frames. Anything else is ignored rather than
+/// erroring, so older/newer clients degrade gracefully.
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe { channel_id: String },
+    Unsubscribe { channel_id: String },
+    Typing { channel_id: String, user_id: String },
+}
+
+impl Actor for GatewaySession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let mut events = self.events.resubscribe();
+        let addr = ctx.address();
+        let fut = async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => addr.do_send(Deliver(event)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        };
+        ctx.spawn(actix::fut::wrap_future(fut));
+    }
+}
+
+#[derive(actix::Message)]
+#[rtype(result = "()")]
+struct Deliver(Event);
+
+impl actix::Handler<Deliver> for GatewaySession {
+    type Result = ();
+
+    fn handle(&mut self, msg: Deliver, ctx: &mut Self::Context) {
+        if !self.subscriptions.contains(msg.0.channel_id()) {
+            return;
+        }
+        if let Ok(payload) = serde_json::to_string(&msg.0) {
+            ctx.text(payload);
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for GatewaySession {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Text(text)) => match serde_json::from_str::<ClientMessage>(&text) {
+                Ok(ClientMessage::Subscribe { channel_id }) => {
+                    self.subscriptions.insert(channel_id);
+                }
+                Ok(ClientMessage::Unsubscribe { channel_id }) => {
+                    self.subscriptions.remove(&channel_id);
+                }
+                Ok(ClientMessage::Typing { channel_id, user_id }) => {
+                    let state = self.state.clone();
+                    let fut = async move { publish(&state, Event::Typing { channel_id, user_id }).await };
+                    ctx.spawn(actix::fut::wrap_future(fut));
+                }
+                Err(_) => {}
+            },
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            _ => {}
+        }
+    }
+}
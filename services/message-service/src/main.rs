@@ -4,16 +4,24 @@ use std::env;
 use tracing_subscriber;
 
 mod api;
+mod federation;
+mod gateway;
 mod models;
 mod services;
 mod config;
 mod db;
 
 use api::routes;
+use models::Event;
 
 pub struct AppState {
     pub db: Database,
     pub redis: Option<redis::Client>,
+    pub events: tokio::sync::broadcast::Sender<Event>,
+    pub http: reqwest::Client,
+    /// Public base URL this instance is reachable at, used to build actor
+    /// ids and inbox/outbox URLs embedded in outgoing activities.
+    pub federation_base_url: String,
 }
 
 #[actix_web::main]
@@ -30,8 +38,20 @@ async fn main() -> std::io::Result<()> {
     let db = client.database(&db_name);
 
     let redis_client = env::var("REDIS_URL").ok().and_then(|url| redis::Client::open(url).ok());
+    let events = gateway::new_bus();
+    let federation_base_url = env::var("FEDERATION_BASE_URL").unwrap_or_else(|_| format!("http://localhost:{port}"));
 
-    let state = web::Data::new(AppState { db, redis: redis_client });
+    let state = web::Data::new(AppState {
+        db,
+        redis: redis_client,
+        events,
+        http: reqwest::Client::new(),
+        federation_base_url,
+    });
+
+    if state.redis.is_some() {
+        actix_web::rt::spawn(gateway::run_redis_relay(state.clone()));
+    }
 
     tracing::info!("Message service starting on port {}", port);
 
@@ -1,8 +1,25 @@
+use bitflags::bitflags;
 use bson::oid::ObjectId;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+bitflags! {
+    /// Compact, forward-compatible replacement for the scattered per-state
+    /// booleans a message used to carry (just `is_pinned`, historically).
+    /// Stored as a single integer; the `Serialize`/`Deserialize` derives
+    /// below go through bitflags' own `serde` feature support.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct MessageFlags: u32 {
+        const PINNED = 1 << 0;
+        const SUPPRESS_EMBEDS = 1 << 1;
+        const EPHEMERAL = 1 << 2;
+        const CROSSPOSTED = 1 << 3;
+        const HAS_THREAD = 1 << 4;
+        const URGENT = 1 << 5;
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
 pub struct Message {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
@@ -24,11 +41,88 @@ pub struct Message {
     pub edited_at: Option<DateTime<Utc>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub deleted_at: Option<DateTime<Utc>>,
-    pub is_pinned: bool,
+    pub flags: MessageFlags,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poll: Option<Poll>,
+    /// Set when this message was federated in from a remote ActivityPub
+    /// `Note`, holding that object's `id` so retries and `Update`/`Delete`
+    /// activities can be matched back to it idempotently.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_id: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// Hand-written so documents written before the `is_pinned` -> `flags`
+/// migration keep working: a stored `is_pinned: true` is folded into
+/// `MessageFlags::PINNED`, whether or not the document also has `flags`.
+impl<'de> Deserialize<'de> for Message {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Raw {
+            #[serde(rename = "_id", default)]
+            id: Option<ObjectId>,
+            channel_id: String,
+            user_id: String,
+            content: String,
+            #[serde(default)]
+            thread_id: Option<String>,
+            #[serde(default)]
+            parent_message_id: Option<String>,
+            message_type: MessageType,
+            #[serde(default)]
+            attachments: Vec<Attachment>,
+            #[serde(default)]
+            mentions: Vec<String>,
+            #[serde(default)]
+            reactions: Vec<Reaction>,
+            #[serde(default)]
+            edited_at: Option<DateTime<Utc>>,
+            #[serde(default)]
+            deleted_at: Option<DateTime<Utc>>,
+            #[serde(default)]
+            is_pinned: bool,
+            #[serde(default)]
+            flags: Option<MessageFlags>,
+            #[serde(default)]
+            poll: Option<Poll>,
+            #[serde(default)]
+            remote_id: Option<String>,
+            created_at: DateTime<Utc>,
+            updated_at: DateTime<Utc>,
+        }
+
+        let raw = Raw::deserialize(deserializer)?;
+        let mut flags = raw.flags.unwrap_or(MessageFlags::empty());
+        if raw.is_pinned {
+            flags |= MessageFlags::PINNED;
+        }
+
+        Ok(Message {
+            id: raw.id,
+            channel_id: raw.channel_id,
+            user_id: raw.user_id,
+            content: raw.content,
+            thread_id: raw.thread_id,
+            parent_message_id: raw.parent_message_id,
+            message_type: raw.message_type,
+            attachments: raw.attachments,
+            mentions: raw.mentions,
+            reactions: raw.reactions,
+            edited_at: raw.edited_at,
+            deleted_at: raw.deleted_at,
+            flags,
+            poll: raw.poll,
+            remote_id: raw.remote_id,
+            created_at: raw.created_at,
+            updated_at: raw.updated_at,
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum MessageType {
@@ -51,6 +145,17 @@ pub struct Attachment {
     pub url: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub thumbnail_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Playback length for audio/video attachments, e.g. voice notes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<f32>,
+    /// Base64-encoded, downsampled amplitude envelope so clients can render
+    /// a scrubbable waveform without decoding the whole audio file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub waveform: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -80,6 +185,16 @@ pub struct UpdateMessageRequest {
     pub content: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RemoveReactionQuery {
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RetractVoteQuery {
+    pub user_id: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AddReactionRequest {
     pub user_id: String,
@@ -100,4 +215,146 @@ pub struct MessagesResponse {
     pub messages: Vec<Message>,
     pub has_more: bool,
     pub cursor: Option<String>,
+    /// Only populated by `search_messages`: the total number of messages
+    /// matching the query, independent of how many fit in `messages`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_hits: Option<u64>,
+    /// Only populated by `search_messages`: for each entry in `messages`, the
+    /// small window of messages immediately before/after it in the same
+    /// channel (same index as the hit it belongs to), so a result can be
+    /// rendered in context rather than in isolation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<Vec<Vec<Message>>>,
+}
+
+/// Structured filters for the search endpoint. Unlike `MessageQueryParams`
+/// there is no `before`/`after` cursor: a search returns the newest `limit`
+/// hits. List-valued filters are
+/// accepted as comma-separated strings since query strings don't carry
+/// repeated keys cleanly through `web::Query`.
+#[derive(Debug, Deserialize)]
+pub struct MessageSearchQuery {
+    pub channel_id: Option<String>,
+    pub content: Option<String>,
+    pub author_ids: Option<String>,
+    pub message_type: Option<MessageType>,
+    /// Comma-separated subset of `attachment`, `link`, `poll`.
+    pub has: Option<String>,
+    pub mentions: Option<String>,
+    #[serde(default)]
+    pub pinned_only: bool,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    /// Number of messages of surrounding context to include on each side of
+    /// a hit, so the UI can render a result in place rather than in isolation.
+    pub context: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Real-time events fanned out over the gateway from the same write paths
+/// that persist to MongoDB, tagged so clients can dispatch on `event`
+/// without a separate envelope type per message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    MessageCreate { payload: Message },
+    MessageUpdate { payload: Message },
+    MessageDelete { channel_id: String, message_id: String, deleted_at: DateTime<Utc> },
+    ReactionAdd { channel_id: String, message_id: String, emoji: String, user_id: String },
+    ReactionRemove { channel_id: String, message_id: String, emoji: String, user_id: String },
+    Typing { channel_id: String, user_id: String },
+}
+
+/// Embedded on a `Message` when `message_type` is `Poll`. `voters` records
+/// each ballot so votes can be retracted or changed. The vote/tally
+/// endpoints return `PollResults`, which exposes only aggregate counts;
+/// `voters` is still included wherever the whole `Message` is serialized
+/// (message fetch/list/search responses and gateway events).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Poll {
+    pub question: String,
+    pub options: Vec<PollOption>,
+    #[serde(default)]
+    pub allow_multiselect: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub voters: Vec<PollVote>,
+}
+
+impl Poll {
+    pub fn results(&self) -> PollResults {
+        PollResults {
+            question: self.question.clone(),
+            options: self.options.clone(),
+            allow_multiselect: self.allow_multiselect,
+            expires_at: self.expires_at,
+            total_votes: self.voters.len() as u32,
+        }
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PollOption {
+    pub id: String,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emoji: Option<String>,
+    #[serde(default)]
+    pub vote_count: u32,
+}
+
+/// One user's ballot. Multiple `option_ids` only when `allow_multiselect`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PollVote {
+    pub user_id: String,
+    pub option_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatePollRequest {
+    pub channel_id: String,
+    pub user_id: String,
+    pub question: String,
+    pub options: Vec<String>,
+    #[serde(default)]
+    pub allow_multiselect: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CastVoteRequest {
+    pub user_id: String,
+    pub option_ids: Vec<String>,
+}
+
+/// Aggregate-only view of a `Poll`: vote tallies per option and a total,
+/// with no per-user breakdown, so a poll's ballots stay private to anyone
+/// calling the vote/tally endpoints while it's still open.
+#[derive(Debug, Serialize)]
+pub struct PollResults {
+    pub question: String,
+    pub options: Vec<PollOption>,
+    pub allow_multiselect: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    pub total_votes: u32,
+}
+
+impl Event {
+    pub fn channel_id(&self) -> &str {
+        match self {
+            Event::MessageCreate { payload } => &payload.channel_id,
+            Event::MessageUpdate { payload } => &payload.channel_id,
+            Event::MessageDelete { channel_id, .. } => channel_id,
+            Event::ReactionAdd { channel_id, .. } => channel_id,
+            Event::ReactionRemove { channel_id, .. } => channel_id,
+            Event::Typing { channel_id, .. } => channel_id,
+        }
+    }
 }